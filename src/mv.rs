@@ -3,6 +3,7 @@ use std::{
     env::args,
     ffi::CString,
     fs,
+    io::{self, IsTerminal, Read, Write},
     path::{Path, PathBuf},
 };
 
@@ -11,6 +12,13 @@ use clap::{Args, Parser};
 use libc::rename;
 
 const PROGRAM: &str = "mv";
+// Chunk size for the streaming copy used by --progress.
+const PROGRESS_CHUNK_SIZE: usize = 64 * 1024;
+// Only show a byte-level bar for files at least this big, so scripted moves
+// of small files stay quiet.
+const PROGRESS_MIN_BYTES: u64 = 10 * 1024 * 1024;
+// Only show the overall "N/total" count when moving at least this many sources.
+const PROGRESS_MIN_FILES: usize = 3;
 
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -35,13 +43,13 @@ struct Cli {
     // Done
     #[arg(long = "debug", help = "Debug, also activates verbose")]
     debug: bool,
-    // TODO
+    // Done
     #[arg(
         long = "exchange",
         help = "Exchange source and destination (swap them)"
     )]
     exchange: bool,
-    // TODO
+    // Done
     #[command(flatten)]
     destructive_actions: DestructiveActions,
     // Done
@@ -74,7 +82,13 @@ struct Cli {
         help = "Treat destination as a normal file"
     )]
     no_target_directory: bool,
-    // TODO
+    // Done
+    #[arg(
+        long = "progress",
+        help = "Display a progress bar when falling back to copying"
+    )]
+    progress: bool,
+    // Done
     #[arg(long = "update", help = "Control which existing files are updated")]
     update: Option<Update>,
     // Done
@@ -85,21 +99,21 @@ struct Cli {
 #[derive(Args, Clone, Copy, Debug)]
 #[group(required = false, multiple = false)]
 struct DestructiveActions {
-    // TODO
+    // Done
     #[arg(
         short = 'f',
         long = "force",
         help = "Do not prompt before destructive actions"
     )]
     force: bool,
-    // TODO
+    // Done
     #[arg(
         short = 'i',
         long = "interactive",
         help = "Prompt before destructive actions, opposite of force"
     )]
     interactive: bool,
-    // TODO
+    // Done
     #[arg(
         short = 'n',
         long = "no-clobber",
@@ -109,7 +123,7 @@ struct DestructiveActions {
 }
 
 #[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum Choice {
+pub enum Choice {
     /// Never make backups, even if --backup is given
     None,
     /// Alias of none
@@ -140,7 +154,7 @@ impl fmt::Display for Choice {
 }
 
 #[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
-enum Update {
+pub enum Update {
     /// Every file in destination is replaced
     #[default]
     All,
@@ -153,6 +167,73 @@ enum Update {
     Older,
 }
 
+/// Resolved move behavior, independent of where it came from. `main()` below
+/// builds one of these from the parsed `Cli`, but anything embedding
+/// blutils' `mv` (the way nushell embeds coreutils) can build one directly
+/// and call [`move_files`] without ever going through clap.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub backup_choice: Option<Choice>,
+    pub backup: bool,
+    pub debug: bool,
+    pub exchange: bool,
+    pub force: bool,
+    pub interactive: bool,
+    pub no_clobber: bool,
+    pub no_copy: bool,
+    pub strip_trailing_slashes: bool,
+    pub suffix: Option<String>,
+    pub target_directory: bool,
+    pub no_target_directory: bool,
+    pub progress: bool,
+    pub update: Update,
+    pub verbose: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            backup_choice: None,
+            backup: false,
+            debug: false,
+            exchange: false,
+            force: false,
+            interactive: false,
+            no_clobber: false,
+            no_copy: false,
+            strip_trailing_slashes: false,
+            suffix: None,
+            target_directory: false,
+            no_target_directory: false,
+            progress: false,
+            update: Update::All,
+            verbose: false,
+        }
+    }
+}
+
+impl From<&Cli> for Options {
+    fn from(cli: &Cli) -> Self {
+        Options {
+            backup_choice: cli.backup_choice,
+            backup: cli.backup,
+            debug: cli.debug,
+            exchange: cli.exchange,
+            force: cli.destructive_actions.force,
+            interactive: cli.destructive_actions.interactive,
+            no_clobber: cli.destructive_actions.no_clobber,
+            no_copy: cli.no_copy,
+            strip_trailing_slashes: cli.strip_trailing_slashes,
+            suffix: cli.suffix.clone(),
+            target_directory: cli.target_directory,
+            no_target_directory: cli.no_target_directory,
+            progress: cli.progress,
+            update: cli.update.unwrap_or_default(),
+            verbose: cli.verbose,
+        }
+    }
+}
+
 pub fn main() {
     let cli: Cli;
     // skip first arg if it happens to be "blutils"
@@ -166,38 +247,141 @@ pub fn main() {
     } else {
         cli = Cli::parse();
     };
-    for p in &cli.source {
-        log(cli.verbose || cli.debug, format!("Moving {}", p.display()));
-        backup(&cli, p);
-        mv(&cli, p);
+
+    let opts = Options::from(&cli);
+    wrap(move_files(&cli.source, &cli.destination, &opts), PROGRAM);
+}
+
+/// Moves every path in `sources` to `dest` according to `opts`. This never
+/// logs-and-exits: every failure is surfaced through the returned
+/// `io::Result`, so callers embedding blutils can decide for themselves how
+/// to report it (e.g. `main()` below prints it via `wrap`). All sources are
+/// attempted even if one fails. Once the rest are done, a single failure is
+/// returned as its own error; two or more are reported as one error
+/// summarizing how many failed.
+pub fn move_files(sources: &[PathBuf], dest: &Path, opts: &Options) -> io::Result<()> {
+    let mut errors: Vec<io::Error> = Vec::new();
+    let show_count =
+        opts.progress && sources.len() >= PROGRESS_MIN_FILES && io::stderr().is_terminal();
+
+    for (i, p) in sources.iter().enumerate() {
+        match should_update(opts, p, dest) {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        }
+
+        // The overwrite decision gates both the backup and the move itself:
+        // if we're not going to touch `dest`, there's nothing to back it up
+        // against, so this must run before `backup_one()` too.
+        if dest.try_exists().unwrap_or(false) && !confirm_overwrite(opts, dest) {
+            continue;
+        }
+
+        if show_count {
+            eprintln!("[{}/{}] {}", i + 1, sources.len(), p.display());
+        }
+        log(
+            opts.verbose || opts.debug,
+            format!("Moving {}", p.display()),
+        );
+
+        if let Err(e) = backup_one(opts, p, dest) {
+            errors.push(e);
+            continue;
+        }
+        if let Err(e) = move_one(opts, p, dest) {
+            errors.push(e);
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.into_iter().next().unwrap()),
+        n => Err(io::Error::other(format!(
+            "mv: {n} of {} sources failed to move",
+            sources.len()
+        ))),
     }
 }
 
-fn backup(cli: &Cli, p: &PathBuf) {
+// Decides, per `--update`, whether `p` should be moved at all. Must run
+// before `backup_one()`/`move_one()` so we never back up or touch a file
+// we're about to skip.
+fn should_update(opts: &Options, p: &Path, dest: &Path) -> io::Result<bool> {
+    if opts.update == Update::All || !dest.try_exists().unwrap_or(false) {
+        return Ok(true);
+    }
+
+    match opts.update {
+        Update::All => Ok(true),
+        Update::None => {
+            log(
+                opts.verbose || opts.debug,
+                format!(
+                    "Skipped {}, destination exists and --update=none was given",
+                    p.display()
+                ),
+            );
+            Ok(false)
+        }
+        Update::Nonefail => {
+            log(
+                opts.verbose || opts.debug,
+                format!(
+                    "Skipped {}, destination exists and --update=none-fail was given",
+                    p.display()
+                ),
+            );
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{}: not moving, destination already exists (--update=none-fail)",
+                    p.display()
+                ),
+            ))
+        }
+        Update::Older => {
+            let src_time = fs::metadata(p).and_then(|m| m.modified());
+            let dst_time = fs::metadata(dest).and_then(|m| m.modified());
+            match (src_time, dst_time) {
+                (Ok(src_time), Ok(dst_time)) => Ok(src_time > dst_time),
+                // If either mtime can't be read, fall back to moving so we
+                // don't silently drop data we can't reason about.
+                _ => Ok(true),
+            }
+        }
+    }
+}
+
+fn backup_one(opts: &Options, p: &Path, dest: &Path) -> io::Result<()> {
     // Checking for options and if the file exists
-    if (!cli.backup && !cli.backup_choice.is_some()) || cli.destination.try_exists().is_err() {
-        return;
+    if (!opts.backup && opts.backup_choice.is_none()) || !dest.try_exists().unwrap_or(false) {
+        return Ok(());
     };
 
-    let suffix = cli.suffix.clone().unwrap_or(String::from("~"));
-    let mut backup_path = format!("{}{}", cli.destination.display(), suffix);
-    let choice = cli.backup_choice.unwrap_or(Choice::Existing);
+    let suffix = opts.suffix.clone().unwrap_or(String::from("~"));
+    let mut backup_path = format!("{}{}", dest.display(), suffix);
+    let choice = opts.backup_choice.unwrap_or(Choice::Existing);
 
     log(
-        cli.verbose || cli.debug,
+        opts.verbose || opts.debug,
         format!("Starting backup with choice {}", choice),
     );
 
     if choice == Choice::Nil || choice == Choice::Existing {
         if !Path::new(&backup_path).exists() {
-            _ = wrap(fs::copy(p, backup_path), PROGRAM);
+            fs::copy(p, backup_path)?;
         } else {
             let mut i = 0;
             loop {
-                backup_path = format!("{}{}{}", cli.destination.display(), suffix, i);
+                backup_path = format!("{}{}{}", dest.display(), suffix, i);
                 if !Path::new(&backup_path).exists() {
-                    _ = wrap(fs::copy(p, backup_path), PROGRAM);
-                    log(cli.verbose || cli.debug, "Backup successful");
+                    fs::copy(p, backup_path)?;
+                    log(opts.verbose || opts.debug, "Backup successful");
                     break;
                 }
                 i = i + 1;
@@ -206,26 +390,141 @@ fn backup(cli: &Cli, p: &PathBuf) {
     } else if choice == Choice::Numbered || choice == Choice::T {
         let mut i = 0;
         loop {
-            backup_path = format!("{}{}{}", cli.destination.display(), suffix, i);
+            backup_path = format!("{}{}{}", dest.display(), suffix, i);
             if !Path::new(&backup_path).exists() {
-                _ = wrap(fs::copy(p, backup_path), PROGRAM);
-                log(cli.verbose || cli.debug, "Backup successful");
+                fs::copy(p, backup_path)?;
+                log(opts.verbose || opts.debug, "Backup successful");
                 break;
             }
             i = i + 1;
         }
     } else if choice == Choice::Simple || choice == Choice::Never {
-        _ = wrap(fs::copy(p, backup_path), PROGRAM);
-        log(cli.verbose || cli.debug, "Backup successful");
+        fs::copy(p, backup_path)?;
+        log(opts.verbose || opts.debug, "Backup successful");
+    }
+
+    Ok(())
+}
+
+// Asks the user whether `dest` should be overwritten, respecting the
+// `force`/`interactive`/`no_clobber` destructive actions group. Returns
+// `true` when the move should go ahead.
+fn confirm_overwrite(opts: &Options, dest: &Path) -> bool {
+    if opts.no_clobber {
+        debug(
+            opts.debug,
+            format!(
+                "Skipped {}, destination exists and --no-clobber was given",
+                dest.display()
+            ),
+        );
+        return false;
+    }
+
+    if opts.interactive {
+        eprint!("mv: overwrite '{}'? ", dest.display());
+        _ = io::stderr().flush();
+
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).is_err() {
+            return false;
+        }
+        return matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+    }
+
+    // `force` is the default, so there's nothing to check for it: just proceed.
+    true
+}
+
+// Copies `src` to `dst` (recursing into directories so a cross-filesystem
+// move of a whole tree works the same as `fs::rename` would) and, only once
+// every file has been copied successfully, unlinks `src` so this behaves
+// like a move rather than a copy.
+fn copy_then_remove(opts: &Options, src: &Path, dst: &Path) -> io::Result<()> {
+    if src.is_dir() {
+        copy_dir_recursive(opts, src, dst)?;
+        fs::remove_dir_all(src)
+    } else {
+        copy_file_with_progress(opts, src, dst)?;
+        fs::remove_file(src)
+    }
+}
+
+fn copy_dir_recursive(opts: &Options, src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        // `DirEntry::file_type()` reports the symlink itself rather than
+        // following it, so this must be checked before `is_dir()` — otherwise
+        // a symlink would fall into the file branch below and get copied as
+        // a plain file containing whatever it pointed at.
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            let target = fs::read_link(&src_path)?;
+            std::os::unix::fs::symlink(target, &dst_path)?;
+        } else if file_type.is_dir() {
+            copy_dir_recursive(opts, &src_path, &dst_path)?;
+        } else {
+            copy_file_with_progress(opts, &src_path, &dst_path)?;
+        }
+    }
+    Ok(())
+}
+
+// Streams `src` into `dst` in fixed-size chunks instead of calling
+// `fs::copy`, so a byte-level bar can be updated as the copy progresses.
+// The bar itself is only drawn when `--progress` was given, stderr is a
+// TTY, and the file is big enough that a bar is actually useful.
+fn copy_file_with_progress(opts: &Options, src: &Path, dst: &Path) -> io::Result<()> {
+    let mut reader = fs::File::open(src)?;
+    let mut writer = fs::File::create(dst)?;
+    let src_metadata = reader.metadata()?;
+    let total = src_metadata.len();
+
+    let show_bar = opts.progress && total >= PROGRESS_MIN_BYTES && io::stderr().is_terminal();
+
+    let mut buf = [0u8; PROGRESS_CHUNK_SIZE];
+    let mut copied = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+
+        if show_bar {
+            print_progress(&dst.display().to_string(), copied, total);
+        }
+    }
+
+    if show_bar {
+        eprintln!();
     }
+
+    // `fs::copy` preserves the source's mode bits on Unix; match that here
+    // now that we stream the copy ourselves instead.
+    fs::set_permissions(dst, src_metadata.permissions())
 }
 
-fn mv(cli: &Cli, p: &PathBuf) {
+fn print_progress(label: &str, done: u64, total: u64) {
+    let pct = done
+        .checked_mul(100)
+        .and_then(|scaled| scaled.checked_div(total))
+        .unwrap_or(100);
+    eprint!("\r{label}: {pct}% ({done}/{total} bytes)");
+    _ = io::stderr().flush();
+}
+
+fn move_one(opts: &Options, p: &Path, dest: &Path) -> io::Result<()> {
     let source: CString;
     // If option is enabled, remove trailing slashes from source
     //
     // This also applies to no_target_directory
-    if cli.strip_trailing_slashes || cli.no_target_directory {
+    if opts.strip_trailing_slashes || opts.no_target_directory {
         // Copy into a string since we need string manipulation for this!
         let mut source_copy = p.to_str().to_owned().unwrap().to_string();
         while source_copy.ends_with("/") {
@@ -235,7 +534,7 @@ fn mv(cli: &Cli, p: &PathBuf) {
         // When it doesnt end with a slash the loop ends and we create a CString from our new
         // string
         source = CString::new(source_copy).unwrap();
-    } else if cli.target_directory {
+    } else if opts.target_directory {
         let mut source_copy = p.to_str().to_owned().unwrap().to_string();
         if !source_copy.ends_with("/") {
             source_copy.push('/');
@@ -244,34 +543,64 @@ fn mv(cli: &Cli, p: &PathBuf) {
     } else {
         source = CString::new(p.to_str().unwrap()).unwrap();
     };
-    let dest = CString::new(cli.destination.to_str().unwrap()).unwrap();
-    
+    let dest_cstr = CString::new(dest.to_str().unwrap()).unwrap();
 
     debug(
-        cli.debug,
+        opts.debug,
         format!(
             "Debug: Source: {}, Destination: {}",
             &source.to_str().unwrap(),
-            &dest.to_str().unwrap()
+            &dest_cstr.to_str().unwrap()
         ),
     );
-    debug(cli.debug, "Entering unsafe statement");
-
-
-    unsafe {
-        let rename_result = libc_wrap(rename(source.as_ptr(), dest.as_ptr()));
-        if rename_result.is_err() {
-            if !cli.no_copy {
-                log(
-                    cli.verbose || cli.debug,
-                    "Renaming failed, copying instead!",
-                );
-                wrap(fs::copy(p, cli.destination.clone()), PROGRAM);
-                log(cli.verbose || cli.debug, "Copying was successful!");
+    debug(opts.debug, "Entering unsafe statement");
+
+    let result = unsafe {
+        if opts.exchange {
+            // RENAME_EXCHANGE is atomic and has no copy-based equivalent, so
+            // --exchange always bypasses the no-copy fallback below.
+            let exchange_result = libc_wrap(libc::renameat2(
+                libc::AT_FDCWD,
+                source.as_ptr(),
+                libc::AT_FDCWD,
+                dest_cstr.as_ptr(),
+                libc::RENAME_EXCHANGE,
+            ));
+            if let Err(e) = &exchange_result {
+                match e.raw_os_error() {
+                    Some(code) if code == libc::ENOENT => log(
+                        true,
+                        "mv: cannot exchange: both SOURCE and DEST must already exist",
+                    ),
+                    Some(code) if code == libc::EINVAL => {
+                        log(true, "mv: --exchange is not supported by this kernel")
+                    }
+                    _ => (),
+                }
+            }
+            exchange_result
+        } else {
+            let rename_result = libc_wrap(rename(source.as_ptr(), dest_cstr.as_ptr()));
+            if rename_result.is_err() {
+                if !opts.no_copy {
+                    log(
+                        opts.verbose || opts.debug,
+                        "Renaming failed, copying instead!",
+                    );
+                    let copy_result = copy_then_remove(opts, p, dest);
+                    if copy_result.is_ok() {
+                        log(opts.verbose || opts.debug, "Copying was successful!");
+                    }
+                    copy_result
+                } else {
+                    rename_result
+                }
             } else {
-                wrap(rename_result, PROGRAM);
+                Ok(())
             }
         }
-        debug(cli.debug, "Exiting unsafe statement");
     };
+
+    debug(opts.debug, "Exiting unsafe statement");
+    result
 }